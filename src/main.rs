@@ -1,19 +1,82 @@
 use actix_web::{web, App, HttpResponse, HttpRequest, HttpServer, Responder, http::StatusCode};
+use actix_web::http::header::{self, HeaderName};
+use arc_swap::ArcSwap;
+use awc::Client;
+use bytes::Bytes;
+use futures::stream;
+use handlebars::Handlebars;
+use hotwatch::{Event, EventKind, Hotwatch};
 use log::{info, warn, debug, LevelFilter};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
 use std::collections::HashMap;
 use std::fs;
+use std::time::Duration;
+use tokio::time::sleep;
 use gethostname::gethostname;
 
+// Body size limit for request extractors, raised well past actix's 256 KB default so the
+// reverse-proxy passthrough can forward realistically-sized payloads
+const MAX_BODY_BYTES: usize = 10 * 1024 * 1024;
+
+// A single frame emitted by an SSE-mode route
+#[derive(Serialize, Deserialize, Clone)]
+struct SseEvent {
+    data: String,
+    event: Option<String>,
+    id: Option<String>,
+}
+
+impl SseEvent {
+    // Render this event in the `text/event-stream` wire format
+    fn frame(&self) -> String {
+        let mut frame = String::new();
+        if let Some(event) = &self.event {
+            frame.push_str(&format!("event: {}\n", event));
+        }
+        if let Some(id) = &self.id {
+            frame.push_str(&format!("id: {}\n", id));
+        }
+        frame.push_str(&format!("data: {}\n\n", self.data));
+        frame
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 struct Route {
     method: String,
     path: String,
     response: String,
     code: u16,
-    error: bool
+    error: bool,
+    // SSE mode: set response_mode to "sse" and provide events + interval_ms
+    #[serde(default)]
+    response_mode: Option<String>,
+    #[serde(default)]
+    interval_ms: Option<u64>,
+    #[serde(default)]
+    events: Option<Vec<SseEvent>>,
+    #[serde(default)]
+    sse_repeat: bool,
+    // Simulated upstream latency: a fixed delay plus optional random jitter on top
+    #[serde(default)]
+    delay_ms: Option<u64>,
+    #[serde(default)]
+    delay_jitter_ms: Option<u64>,
+    // Self-documentation and argument-count validation
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    hidden: bool,
+    #[serde(default)]
+    min_args: Option<usize>,
+    #[serde(default)]
+    max_args: Option<usize>,
+    // Passthrough mode: forward matching requests to a real upstream instead of responding locally
+    #[serde(default)]
+    proxy_to: Option<String>,
 }
 
 impl Default for Route {
@@ -24,14 +87,267 @@ impl Default for Route {
             response: "OK".to_string(),
             code: 200,
             error: false,
+            response_mode: None,
+            interval_ms: None,
+            events: None,
+            sse_repeat: false,
+            delay_ms: None,
+            delay_jitter_ms: None,
+            description: None,
+            hidden: false,
+            min_args: None,
+            max_args: None,
+            proxy_to: None,
         }
     }
 }
 
+// Summary of a route returned by GET /routes
+#[derive(Serialize)]
+struct RouteSummary {
+    method: String,
+    path: String,
+    code: u16,
+    description: Option<String>,
+}
+
+// Count distinct query parameter names (a repeated key counts once), the "arguments"
+// min_args/max_args constrain. Routes are looked up by
+// exact full-path key, so the path-segment count is fixed for a given route and would only
+// muddy the bounds if folded in here.
+fn count_args(query_string: &str) -> usize {
+    parse_query(query_string).len()
+}
+
+// Turn a fixed delay plus optional jitter into an actual duration to sleep for
+fn delay_duration(delay_ms: u64, jitter_ms: u64) -> Duration {
+    let jitter = if jitter_ms > 0 {
+        rand::thread_rng().gen_range(0..=jitter_ms)
+    } else {
+        0
+    };
+    Duration::from_millis(delay_ms + jitter)
+}
+
+// Build a streaming `text/event-stream` response for a route with response_mode: "sse"
+fn sse_response(status_code: StatusCode, route: &Route) -> HttpResponse {
+    let events = route.events.clone().unwrap_or_default();
+    let interval = Duration::from_millis(route.interval_ms.unwrap_or(1000));
+    let repeat = route.sse_repeat;
+
+    let stream = stream::unfold((0usize, events), move |(idx, events)| async move {
+        if events.is_empty() {
+            return None;
+        }
+        let next_idx = if idx >= events.len() {
+            if !repeat {
+                return None;
+            }
+            0
+        } else {
+            idx
+        };
+        sleep(interval).await;
+        let frame = events[next_idx].frame();
+        Some((Ok::<_, actix_web::Error>(Bytes::from(frame)), (next_idx + 1, events)))
+    });
+
+    HttpResponse::build(status_code)
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(stream)
+}
+
 // Shared state for dynamic routes and server configuration
 struct AppState {
-    dynamic_routes: Mutex<HashMap<String, Route>>,
-    error_percentage: Mutex<u8>,
+    // Lock-free so the hot read path never blocks readers against a writer
+    dynamic_routes: ArcSwap<HashMap<String, Route>>,
+    error_percentage: AtomicU8,
+    // Global chaos-testing latency: applied to a sampled fraction of requests
+    latency_percentage: Mutex<u8>,
+    delay_ms: Mutex<u64>,
+    delay_jitter_ms: Mutex<u64>,
+    // Renders a route's `response` field as a template for each request
+    handlebars: Handlebars<'static>,
+}
+
+// Per-request values exposed to a route's Handlebars template
+#[derive(Serialize)]
+struct TemplateContext {
+    path: String,
+    segments: Vec<String>,
+    query: HashMap<String, String>,
+    headers: HashMap<String, String>,
+    method: String,
+    body: String,
+}
+
+// Parse a raw query string into a flat key/value map (last value wins on duplicate keys)
+fn parse_query(query_string: &str) -> HashMap<String, String> {
+    query_string
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let key = parts.next()?;
+            let value = parts.next().unwrap_or("");
+            Some((key.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+// Build the template context for a matched dynamic route
+fn build_template_context(path: &str, req: &HttpRequest, body: &str) -> TemplateContext {
+    let segments = path.split('/').filter(|s| !s.is_empty()).map(|s| s.to_string()).collect();
+    let headers = req.headers()
+        .iter()
+        .map(|(name, value)| (name.as_str().to_string(), value.to_str().unwrap_or("").to_string()))
+        .collect();
+
+    TemplateContext {
+        path: path.to_string(),
+        segments,
+        query: parse_query(req.query_string()),
+        headers,
+        method: req.method().to_string(),
+        body: body.to_string(),
+    }
+}
+
+// Parse a routes.yml document into the top-level YAML config map
+fn parse_yaml_config(content: &str) -> Result<HashMap<String, serde_yaml::Value>, String> {
+    serde_yaml::from_str(content).map_err(|e| format!("failed to parse YAML: {}", e))
+}
+
+// Read a config value as an unsigned integer, accepting either a bare YAML integer
+// (as the per-route delay_ms/delay_jitter_ms fields do) or a quoted string
+fn parse_config_uint(value: Option<&serde_yaml::Value>) -> Option<u64> {
+    let value = value?;
+    value.as_u64().or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+}
+
+// Pull the dynamic routes map and error percentage out of a parsed config
+fn parse_routes_and_error_percentage(config: &HashMap<String, serde_yaml::Value>) -> Result<(HashMap<String, Route>, u8), String> {
+    let routes_value = config.get("routes").ok_or_else(|| "missing 'routes' key".to_string())?;
+    let dynamic_routes: HashMap<String, Route> = serde_yaml::from_value(routes_value.clone())
+        .map_err(|e| format!("failed to parse routes: {}", e))?;
+
+    let error_percentage = config.get("error_percentage")
+        .and_then(|v| v.as_str())
+        .and_then(|e| e.parse().ok())
+        .unwrap_or(0);
+
+    Ok((dynamic_routes, error_percentage))
+}
+
+// Re-read and re-parse routes.yml, swapping the result into shared state on success.
+// Parse failures are logged rather than crashing the running server.
+fn reload_config(data: &web::Data<AppState>) {
+    let content = match fs::read_to_string("routes.yml") {
+        Ok(content) => content,
+        Err(err) => {
+            warn!("Failed to read routes.yml during reload: {}", err);
+            return;
+        }
+    };
+
+    let config = match parse_yaml_config(&content) {
+        Ok(config) => config,
+        Err(err) => {
+            warn!("Failed to reload routes.yml: {}", err);
+            return;
+        }
+    };
+
+    match parse_routes_and_error_percentage(&config) {
+        Ok((routes, error_percentage)) => {
+            let route_count = routes.len();
+            data.dynamic_routes.store(Arc::new(routes));
+            data.error_percentage.store(error_percentage, Ordering::Relaxed);
+            info!("Reloaded routes.yml: {} route(s), error percentage {}", route_count, error_percentage);
+        }
+        Err(err) => warn!("Failed to reload routes.yml: {}", err),
+    }
+}
+
+// Forward a request to an upstream, rebuilding method/path/query/headers/body, and stream the
+// upstream's response back to the client
+async fn proxy_request(upstream: &str, path: &str, req: &HttpRequest, body: &Bytes) -> HttpResponse {
+    let query = req.query_string();
+    let url = if query.is_empty() {
+        format!("{}/{}", upstream.trim_end_matches('/'), path)
+    } else {
+        format!("{}/{}?{}", upstream.trim_end_matches('/'), path, query)
+    };
+
+    let client = Client::new();
+    // Leave the upstream body exactly as the upstream sent it, so the Content-Encoding
+    // header we forward below still matches the bytes we forward
+    let mut forwarded = client.request(req.method().clone(), &url).no_decompress();
+    for (name, value) in req.headers() {
+        if *name != header::HOST {
+            forwarded = forwarded.insert_header((name.clone(), value.clone()));
+        }
+    }
+
+    match forwarded.send_body(body.clone()).await {
+        Ok(upstream_response) => {
+            let mut client_response = HttpResponse::build(upstream_response.status());
+            for (name, value) in upstream_response.headers() {
+                if is_hop_by_hop_header(name) {
+                    continue;
+                }
+                client_response.insert_header((name.clone(), value.clone()));
+            }
+            // Stream the upstream body back rather than buffering it, so large or
+            // long-lived responses aren't subject to awc's default payload size limit
+            client_response.streaming(upstream_response)
+        }
+        Err(err) => {
+            warn!("Failed to proxy request to {}: {}", url, err);
+            HttpResponse::BadGateway().finish()
+        }
+    }
+}
+
+// Per-hop framing headers that must not be copied onto a freshly-built response
+fn is_hop_by_hop_header(name: &HeaderName) -> bool {
+    *name == header::CONNECTION || *name == header::CONTENT_LENGTH || *name == header::TRANSFER_ENCODING
+}
+
+// Build the Handlebars renderer used for route response templates. Mock responses echo request
+// data (tokens, JSON bodies, query values) verbatim, so HTML escaping must be disabled.
+fn build_handlebars() -> Handlebars<'static> {
+    let mut handlebars = Handlebars::new();
+    handlebars.register_escape_fn(handlebars::no_escape);
+    handlebars
+}
+
+// Render a route's `response` field as a Handlebars template; fall back to the raw string on error
+fn render_route_response(handlebars: &Handlebars, template: &str, path: &str, req: &HttpRequest, body: &str) -> String {
+    let context = build_template_context(path, req, body);
+    match handlebars.render_template(template, &context) {
+        Ok(rendered) => rendered,
+        Err(err) => {
+            warn!("Failed to render template for path {}: {}", path, err);
+            template.to_string()
+        }
+    }
+}
+
+// roll the dice on the global latency_percentage and, if it hits, sleep
+async fn maybe_apply_global_latency(data: &web::Data<AppState>) {
+    let latency_percentage = *data.latency_percentage.lock().unwrap();
+    if latency_percentage == 0 {
+        return;
+    }
+    let roll = rand::thread_rng().gen_range(0..100);
+    if roll < latency_percentage {
+        let delay_ms = *data.delay_ms.lock().unwrap();
+        let jitter_ms = *data.delay_jitter_ms.lock().unwrap();
+        sleep(delay_duration(delay_ms, jitter_ms)).await;
+    }
 }
 
 // echo body back
@@ -60,6 +376,7 @@ async fn status_code(info: web::Path<(u16,)>) -> impl Responder {
 
 // set response to an error code based on general percentage requested
 async fn status_error_percentage(data: web::Data<AppState>) -> impl Responder {
+    maybe_apply_global_latency(&data).await;
     let error_code = error_code_picker(data).await;
     if error_code != 200 {
         HttpResponse::new(actix_web::http::StatusCode::from_u16(error_code as u16).unwrap())
@@ -71,7 +388,7 @@ async fn status_error_percentage(data: web::Data<AppState>) -> impl Responder {
 // pick an error code to return
 async fn error_code_picker(data: web::Data<AppState>) -> u16 {
     let mut rng = rand::thread_rng();
-    let error_percentage = *data.error_percentage.lock().unwrap();
+    let error_percentage = data.error_percentage.load(Ordering::Relaxed);
     let random_number = rng.gen_range(0..100);
     if random_number < error_percentage {
         let errors = vec![400, 401, 403, 408, 409, 500, 502, 503, 504]; // List of possible error status codes
@@ -83,13 +400,10 @@ async fn error_code_picker(data: web::Data<AppState>) -> u16 {
 }
 
 // Handler for dynamic responses
-async fn dynamic_handler(data: web::Data<AppState>, path: web::Path<String>, req: HttpRequest) -> impl Responder {
+async fn dynamic_handler(data: web::Data<AppState>, path: web::Path<String>, req: HttpRequest, req_body: Bytes) -> impl Responder {
     debug!("Entering dynamic route handler for: {}", path);
     let path_clone = path.clone(); // Clone the path before moving it
-    let route = {
-        let routes = data.dynamic_routes.lock().unwrap();
-        routes.get(&*path_clone).cloned()
-    };
+    let route = data.dynamic_routes.load().get(&*path_clone).cloned();
 
     if let Some(route) = route {
         debug!("Handling dynamic route: {} {}", route.method, route.path);
@@ -97,14 +411,36 @@ async fn dynamic_handler(data: web::Data<AppState>, path: web::Path<String>, req
 
         // Check if the request method matches the defined method
         if *req.method() == *route.method {
+            // Enforce the route's declared argument-count bounds, if any
+            let arg_count = count_args(req.query_string());
+            if route.min_args.map_or(false, |min| arg_count < min) || route.max_args.map_or(false, |max| arg_count > max) {
+                return HttpResponse::BadRequest().finish();
+            }
+
+            // Per-route fixed delay, then the global sampled latency knob — only for
+            // requests that are actually going to be served
+            if let Some(delay_ms) = route.delay_ms {
+                sleep(delay_duration(delay_ms, route.delay_jitter_ms.unwrap_or(0))).await;
+            }
+            maybe_apply_global_latency(&data).await;
+
             // If route is set to intentionally error then pass off to error response handler
             if route.error {
                 let error_code = error_code_picker(data).await;
                 let status_code = StatusCode::from_u16(error_code).unwrap_or(StatusCode::OK);
                 return HttpResponse::build(status_code).finish();
+            } else if let Some(proxy_to) = &route.proxy_to {
+                return proxy_request(proxy_to, &path_clone, &req, &req_body).await;
+            } else if route.response_mode.as_deref() == Some("sse") {
+                return sse_response(status_code, &route);
             } else {
                 match route.method.as_str() {
-                    "GET" | "POST" | "PATCH" | "PUT" => HttpResponse::build(status_code).body(route.response),
+                    "GET" | "POST" | "PATCH" | "PUT" => {
+                        // Templates only need text; a non-UTF-8 body is rendered lossily rather than rejected
+                        let body_text = String::from_utf8_lossy(&req_body);
+                        let body = render_route_response(&data.handlebars, &route.response, &path_clone, &req, &body_text);
+                        HttpResponse::build(status_code).body(body)
+                    }
                     "DELETE" => HttpResponse::build(status_code).finish(),
                     _ => HttpResponse::NotFound().finish(), // assume a 404 route does not match dynamic routes
                 }
@@ -123,13 +459,29 @@ async fn dynamic_handler(data: web::Data<AppState>, path: web::Path<String>, req
 async fn update_routes(req_body: String, data: web::Data<AppState>) -> impl Responder {
     info!("Adding new route: {}", req_body);
     let new_routes: Vec<Route> = serde_json::from_str(&req_body).unwrap_or_default();
-    let mut routes = data.dynamic_routes.lock().unwrap();
+    let mut routes: HashMap<String, Route> = (**data.dynamic_routes.load()).clone();
     for route in new_routes {
         routes.insert(route.path.clone(), route);
     }
+    data.dynamic_routes.store(Arc::new(routes));
     HttpResponse::Ok().finish()
 }
 
+// List all configured, non-hidden dynamic routes
+async fn list_routes(data: web::Data<AppState>) -> impl Responder {
+    let routes: Vec<RouteSummary> = data.dynamic_routes.load()
+        .values()
+        .filter(|route| !route.hidden)
+        .map(|route| RouteSummary {
+            method: route.method.clone(),
+            path: route.path.clone(),
+            code: route.code,
+            description: route.description.clone(),
+        })
+        .collect();
+    HttpResponse::Ok().json(routes)
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Initialize the logger
@@ -139,31 +491,23 @@ async fn main() -> std::io::Result<()> {
 
     // Read the initial routes and configuration from a YAML file
     let config: HashMap<String, serde_yaml::Value> = match fs::read_to_string("routes.yml") {
-        Ok(content) => match serde_yaml::from_str(&content) {
+        Ok(content) => match parse_yaml_config(&content) {
             Ok(yaml) => yaml,
-            Err(_) => panic!("Failed to parse YAML from routes.yaml"),
+            Err(err) => panic!("Failed to parse YAML from routes.yml: {}", err),
         },
         Err(_) => panic!("Failed to read routes.yml"),
     };
 
-    // Parse dynamic routes
-    let dynamic_routes: HashMap<String, Route> = if let Some(routes_value) = config.get("routes") {
-        debug!("Loading config: {:?}", config);
-        match serde_yaml::from_value(routes_value.clone()) {
-            Ok(routes) => routes,
-            Err(_) => panic!("Failed to parse routes from routes.yml"),
-        }
-    } else {
-        panic!("Failed to load routes from routes.yml");
-    };
+    debug!("Loading config: {:?}", config);
 
-    
-    // Handle error percentage
-    let error_percentage = if let Some(error_value) = config.get("error_percentage") {
-        error_value.as_str().and_then(|e| e.parse().ok()).unwrap_or(0)
-    } else {
-        0
-    };
+    // Parse dynamic routes and error percentage
+    let (dynamic_routes, error_percentage) = parse_routes_and_error_percentage(&config)
+        .unwrap_or_else(|err| panic!("Failed to load routes from routes.yml: {}", err));
+
+    // Handle the global latency knobs
+    let latency_percentage = parse_config_uint(config.get("latency_percentage")).unwrap_or(0) as u8;
+    let delay_ms = parse_config_uint(config.get("delay_ms")).unwrap_or(0);
+    let delay_jitter_ms = parse_config_uint(config.get("delay_jitter_ms")).unwrap_or(0);
 
     // Log the loaded routes from json
     for (path, route) in &dynamic_routes {
@@ -171,12 +515,29 @@ async fn main() -> std::io::Result<()> {
     }
 
     info!("Setting error percentage to {}", error_percentage);
+    info!("Setting latency percentage to {} (delay {}ms + up to {}ms jitter)", latency_percentage, delay_ms, delay_jitter_ms);
 
     let app_data = web::Data::new(AppState {
-        dynamic_routes: Mutex::new(dynamic_routes),
-        error_percentage: Mutex::new(error_percentage),
+        dynamic_routes: ArcSwap::from_pointee(dynamic_routes),
+        error_percentage: AtomicU8::new(error_percentage),
+        latency_percentage: Mutex::new(latency_percentage),
+        delay_ms: Mutex::new(delay_ms),
+        delay_jitter_ms: Mutex::new(delay_jitter_ms),
+        handlebars: build_handlebars(),
     });
 
+    // Watch routes.yml and hot-reload dynamic_routes/error_percentage on change
+    let mut hotwatch = Hotwatch::new().expect("failed to initialize routes.yml watcher");
+    let watch_data = app_data.clone();
+    hotwatch
+        .watch("routes.yml", move |event: Event| {
+            if let EventKind::Modify(_) = event.kind {
+                info!("routes.yml changed, reloading");
+                reload_config(&watch_data);
+            }
+        })
+        .expect("failed to watch routes.yml");
+
     // Get the server port from the configuration or default to 8080
     let server_port = config.get("port").and_then(|v| v.as_str()).unwrap_or("8080").to_string();
 
@@ -185,14 +546,15 @@ async fn main() -> std::io::Result<()> {
     // Start the server in a new thread
     let server = HttpServer::new(move || {
         App::new()
-        .wrap(actix_web::middleware::Logger::default())    
+        .wrap(actix_web::middleware::Logger::default())
         .app_data(app_data.clone())
+        .app_data(web::PayloadConfig::new(MAX_BODY_BYTES))
             .service(web::resource("/echo").route(web::post().to(echo)))
             .service(web::resource("/host").route(web::get().to(host)))
             .service(web::resource("/healthz").route(web::get().to(healthz)))
             .service(web::resource("/status/{code}").route(web::get().to(status_code)).route(web::post().to(status_code)))
             .service(web::resource("/errors").route(web::get().to(status_error_percentage)))
-            .service(web::resource("/routes").route(web::put().to(update_routes)).route(web::post().to(update_routes)))
+            .service(web::resource("/routes").route(web::get().to(list_routes)).route(web::put().to(update_routes)).route(web::post().to(update_routes)))
             .service(web::resource("/{path:.*}")
                 .route(web::get().to(dynamic_handler))
                 .route(web::post().to(dynamic_handler))